@@ -0,0 +1,264 @@
+use crate::tunnel::Tunnel;
+use barista::command::{CommandError, CommandResponse, CommandResult, Topic};
+use barista::server::{ServerData, ServerStatus};
+use log::warn;
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, Command as ProcessCommand, Stdio};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::UnboundedSender;
+use warp::ws::Message;
+
+/// How many console lines to keep around so a client that subscribes to
+/// `Topic::Console(id)` after the server has been running a while still
+/// gets some history instead of starting from a blank screen.
+const CONSOLE_HISTORY_LEN: usize = 500;
+
+/// Whether the OS still has a process with this pid. Used to sanity-check a
+/// status reloaded from the store, since a persisted `Running`/`Starting`
+/// only reflects reality if the process actually survived the restart.
+fn pid_is_alive(pid: u32) -> bool {
+    Path::new(&format!("/proc/{}", pid)).exists()
+}
+
+/// The daemon's live handle to a configured server: the process-management
+/// counterpart to the plain-data `ServerData` that gets sent to clients.
+pub struct Server {
+    pub data: ServerData,
+    process: Option<Child>,
+    stdin: Option<std::process::ChildStdin>,
+    history: Arc<Mutex<VecDeque<String>>>,
+    tx: UnboundedSender<(Topic, Message)>,
+    tunnel: Option<Tunnel>,
+    /// Bumped by `begin_tunnel_connect`/`disable_tunnel` so an in-flight
+    /// `Tunnel::connect` that finishes after a later `disable_tunnel` (or
+    /// another `EnableTunnel`) can tell its result is stale and disconnect
+    /// it instead of silently reactivating the tunnel.
+    tunnel_generation: u64,
+}
+
+fn console_message(id: usize, line: String) -> Option<Message> {
+    let env = crate::Envelope::Event(CommandResponse::ConsoleLine(id, line));
+    crate::serialize_ws(&env).ok()
+}
+
+fn spawn_reader<R: std::io::Read + Send + 'static>(
+    id: usize,
+    reader: R,
+    history: Arc<Mutex<VecDeque<String>>>,
+    tx: UnboundedSender<(Topic, Message)>,
+) {
+    std::thread::spawn(move || {
+        for line in BufReader::new(reader).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+
+            {
+                let mut history = history.lock().unwrap();
+                history.push_back(line.clone());
+                if history.len() > CONSOLE_HISTORY_LEN {
+                    history.pop_front();
+                }
+            }
+
+            if let Err(e) = crate::store::Store::get().append_console_line(id, &line) {
+                warn!("failed to persist console line for server {}: {}", id, e);
+            }
+
+            if let Some(msg) = console_message(id, line) {
+                if tx.send((Topic::Console(id), msg)).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+}
+
+impl Server {
+    /// `data` may carry a status reloaded from the store rather than freshly
+    /// constructed, and we never reattach to a real `Child` on boot — so a
+    /// persisted `Running`/`Starting` is only trustworthy if its pid still
+    /// exists. Anything else gets downgraded to `Stopped` here rather than
+    /// surfacing a ghost status until the next `update_status` poll.
+    pub fn new(mut data: ServerData, tx: UnboundedSender<(Topic, Message)>) -> Self {
+        if data.status != ServerStatus::Stopped && !data.pid.map(pid_is_alive).unwrap_or(false) {
+            data.status = ServerStatus::Stopped;
+            data.pid = None;
+        }
+
+        Self {
+            data,
+            process: None,
+            stdin: None,
+            history: Arc::new(Mutex::new(VecDeque::with_capacity(CONSOLE_HISTORY_LEN))),
+            tx,
+            tunnel: None,
+            tunnel_generation: 0,
+        }
+    }
+
+    pub fn start(&mut self) -> CommandResult {
+        if self.process.is_some() {
+            return Ok(CommandResponse::UpdateServer(self.data.id, self.data.clone()));
+        }
+
+        let mut child = ProcessCommand::new("java")
+            .arg("-jar")
+            .arg(&self.data.config.jar)
+            .current_dir(&self.data.config.directory)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| CommandError::Io(e.to_string()))?;
+
+        let stdout = child.stdout.take().expect("child spawned with piped stdout");
+        let stderr = child.stderr.take().expect("child spawned with piped stderr");
+        self.stdin = child.stdin.take();
+
+        spawn_reader(self.data.id, stdout, self.history.clone(), self.tx.clone());
+        spawn_reader(self.data.id, stderr, self.history.clone(), self.tx.clone());
+
+        self.data.pid = Some(child.id());
+        self.data.status = ServerStatus::Starting;
+        self.process = Some(child);
+        self.save_status();
+
+        Ok(CommandResponse::UpdateServer(self.data.id, self.data.clone()))
+    }
+
+    pub fn stop(&mut self) -> CommandResult {
+        if let Some(mut child) = self.process.take() {
+            if let Err(e) = child.kill() {
+                warn!("failed to kill server {}: {}", self.data.id, e);
+            }
+            self.stdin = None;
+        } else if let Some(pid) = self.data.pid {
+            // There's no `Child` to kill (e.g. this status was reloaded
+            // from the store at boot with a live pid) — signal the OS
+            // process directly so Stop isn't a silent no-op.
+            if let Err(e) = ProcessCommand::new("kill").arg(pid.to_string()).status() {
+                warn!(
+                    "failed to kill orphaned server {} (pid {}): {}",
+                    self.data.id, pid, e
+                );
+            }
+        }
+
+        if self.data.status != ServerStatus::Stopped {
+            self.data.status = ServerStatus::Stopped;
+            self.data.pid = None;
+            self.save_status();
+        }
+
+        Ok(CommandResponse::UpdateServer(self.data.id, self.data.clone()))
+    }
+
+    fn save_status(&self) {
+        if let Err(e) = crate::store::Store::get().save_status(&self.data) {
+            warn!("failed to persist status for server {}: {}", self.data.id, e);
+        }
+    }
+
+    pub fn send_console_command(&mut self, line: &str) -> CommandResult {
+        let stdin = self
+            .stdin
+            .as_mut()
+            .ok_or_else(|| CommandError::Io("server is not running".to_string()))?;
+
+        stdin
+            .write_all(line.as_bytes())
+            .and_then(|_| stdin.write_all(b"\n"))
+            .map_err(|e| CommandError::Io(e.to_string()))?;
+
+        Ok(CommandResponse::UpdateServer(self.data.id, self.data.clone()))
+    }
+
+    /// Snapshot of the last lines printed to this server's console, oldest
+    /// first, for a client that just subscribed to `Topic::Console(id)`.
+    pub fn console_backlog(&self) -> Vec<String> {
+        self.history.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Marks an `EnableTunnel` as starting and returns the generation the
+    /// caller's `Tunnel::connect` result must present to `set_tunnel` for it
+    /// to still count — anything stale (a later `disable_tunnel` or another
+    /// `EnableTunnel`) was superseded before it finished connecting.
+    pub fn begin_tunnel_connect(&mut self) -> u64 {
+        self.tunnel_generation += 1;
+        self.tunnel_generation
+    }
+
+    /// Records a tunnel that has already finished registering with the
+    /// relay, exposing its assigned public address on `data` — unless
+    /// `generation` (from `begin_tunnel_connect`) is no longer current, in
+    /// which case the tunnel is disconnected instead of installed. Any
+    /// existing tunnel is disconnected first so a redundant `EnableTunnel`
+    /// can't leak the tunnel it's replacing.
+    pub fn set_tunnel(&mut self, generation: u64, tunnel: Tunnel, public_address: String) {
+        if generation != self.tunnel_generation {
+            tunnel.disconnect();
+            return;
+        }
+
+        if let Some(old) = self.tunnel.take() {
+            old.disconnect();
+        }
+
+        self.tunnel = Some(tunnel);
+        self.data.tunnel_address = Some(public_address);
+    }
+
+    pub fn disable_tunnel(&mut self) {
+        self.tunnel_generation += 1;
+        if let Some(tunnel) = self.tunnel.take() {
+            tunnel.disconnect();
+        }
+        self.data.tunnel_address = None;
+    }
+
+    pub fn update_status(&mut self) -> bool {
+        let changed = if let Some(child) = &mut self.process {
+            match child.try_wait() {
+                Ok(Some(_)) => {
+                    self.process = None;
+                    self.stdin = None;
+                    self.data.status = ServerStatus::Stopped;
+                    self.data.pid = None;
+                    true
+                }
+                Ok(None) => {
+                    if self.data.status != ServerStatus::Running {
+                        self.data.status = ServerStatus::Running;
+                        true
+                    } else {
+                        false
+                    }
+                }
+                Err(_) => false,
+            }
+        } else if self.data.status != ServerStatus::Stopped {
+            // No `Child` to poll (a status reloaded from the store at boot)
+            // — the pid is the only thing we can check, so a server that
+            // looked alive at boot still gets corrected once it actually exits.
+            if self.data.pid.map(pid_is_alive).unwrap_or(false) {
+                false
+            } else {
+                self.data.status = ServerStatus::Stopped;
+                self.data.pid = None;
+                true
+            }
+        } else {
+            false
+        };
+
+        if changed {
+            self.save_status();
+        }
+
+        changed
+    }
+}
@@ -0,0 +1,86 @@
+use barista::server::ServerData;
+use once_cell::sync::OnceCell;
+use sled::Tree;
+use std::convert::TryInto;
+use std::path::Path;
+
+static STORE: OnceCell<Store> = OnceCell::new();
+
+/// Max lines returned by one `console_history` call. `GetConsoleHistory` is
+/// meant to be paged via its `since` cursor, not to dump an entire
+/// long-lived server's backlog into a single CBOR frame.
+const CONSOLE_HISTORY_PAGE_SIZE: usize = 500;
+
+/// The daemon's durable state: console backlog and last-known server status,
+/// so a restart doesn't lose history or forget what was running. Backed by
+/// an embedded sled database, sliced into one console tree per server plus
+/// a shared status tree, and stashed in a global once-cell so any module
+/// can reach it without threading a handle through every call site.
+pub struct Store {
+    db: sled::Db,
+}
+
+impl Store {
+    /// Opens the database at `path`, making it available via [`Store::get`].
+    /// Must be called exactly once, before any server starts.
+    pub fn init(path: &Path) -> sled::Result<()> {
+        let db = sled::open(path)?;
+        STORE
+            .set(Store { db })
+            .ok()
+            .expect("Store::init called more than once");
+        Ok(())
+    }
+
+    pub fn get() -> &'static Store {
+        STORE.get().expect("Store::init was not called")
+    }
+
+    fn console_tree(&self, id: usize) -> sled::Result<Tree> {
+        self.db.open_tree(format!("console-{}", id))
+    }
+
+    fn status_tree(&self) -> sled::Result<Tree> {
+        self.db.open_tree("status")
+    }
+
+    /// Appends a console line under a monotonically increasing key and
+    /// returns that key, so callers can hand it back as the `since` cursor
+    /// for the next `GetConsoleHistory` page.
+    pub fn append_console_line(&self, id: usize, line: &str) -> sled::Result<u64> {
+        let tree = self.console_tree(id)?;
+        let key = tree.generate_id()?;
+        tree.insert(key.to_be_bytes(), line.as_bytes())?;
+        Ok(key)
+    }
+
+    /// Up to `CONSOLE_HISTORY_PAGE_SIZE` console lines for `id` with a key
+    /// greater than `since`, oldest first. The caller pages through the
+    /// backlog by feeding the last returned key back in as `since`.
+    pub fn console_history(&self, id: usize, since: Option<u64>) -> sled::Result<Vec<(u64, String)>> {
+        let tree = self.console_tree(id)?;
+        let start = since.map(|key| key + 1).unwrap_or(0);
+
+        tree.range(start.to_be_bytes()..)
+            .take(CONSOLE_HISTORY_PAGE_SIZE)
+            .map(|entry| {
+                let (key, value) = entry?;
+                let key = u64::from_be_bytes(key.as_ref().try_into().unwrap());
+                Ok((key, String::from_utf8_lossy(&value).into_owned()))
+            })
+            .collect()
+    }
+
+    pub fn save_status(&self, data: &ServerData) -> sled::Result<()> {
+        let tree = self.status_tree()?;
+        let bytes = serde_cbor::to_vec(data).expect("ServerData always serializes");
+        tree.insert(data.id.to_be_bytes(), bytes)?;
+        Ok(())
+    }
+
+    pub fn load_status(&self, id: usize) -> Option<ServerData> {
+        let tree = self.status_tree().ok()?;
+        let bytes = tree.get(id.to_be_bytes()).ok()??;
+        serde_cbor::from_slice(&bytes).ok()
+    }
+}
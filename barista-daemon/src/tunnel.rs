@@ -0,0 +1,194 @@
+use barista::command::CommandError;
+use futures::{SinkExt, StreamExt};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+/// Wire format spoken with the relay: every inbound player connection is
+/// identified by a `conn_id` and framed as a `Data`/`Close` message, the
+/// same multiplexing-over-a-single-socket scheme the relay uses on its end.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum RelayFrame {
+    Connected { public_address: String },
+    Data { conn_id: u64, payload: Vec<u8> },
+    Close { conn_id: u64 },
+}
+
+struct Connection {
+    tx: mpsc::UnboundedSender<Vec<u8>>,
+    task: JoinHandle<()>,
+}
+
+type Connections = Arc<Mutex<HashMap<u64, Connection>>>;
+
+/// A live tunnel registered with the relay. Calling `disconnect` tears down
+/// the relay connection and every player connection it was multiplexing;
+/// dropping a `Tunnel` without calling it leaves those tasks running.
+pub struct Tunnel {
+    writer_task: JoinHandle<()>,
+    reader_task: JoinHandle<()>,
+    conns: Connections,
+}
+
+impl Tunnel {
+    /// Connects to `relay_url`, waits for the relay to assign a public
+    /// address, then starts pumping player traffic between the relay and
+    /// `127.0.0.1:local_port`.
+    pub async fn connect(relay_url: &str, local_port: u16) -> Result<(Self, String), CommandError> {
+        let (ws, _) = connect_async(relay_url)
+            .await
+            .map_err(|e| CommandError::Io(e.to_string()))?;
+        let (mut ws_tx, mut ws_rx) = ws.split();
+
+        let public_address = match ws_rx.next().await {
+            Some(Ok(WsMessage::Binary(bytes))) => match serde_cbor::from_slice(&bytes) {
+                Ok(RelayFrame::Connected { public_address }) => public_address,
+                _ => {
+                    return Err(CommandError::Io(
+                        "relay did not send a registration frame".to_string(),
+                    ))
+                }
+            },
+            _ => return Err(CommandError::Io("relay closed before registering".to_string())),
+        };
+
+        let (to_relay_tx, mut to_relay_rx) = mpsc::unbounded_channel::<RelayFrame>();
+        let conns: Connections = Arc::new(Mutex::new(HashMap::new()));
+
+        let writer_task = tokio::spawn(async move {
+            while let Some(frame) = to_relay_rx.recv().await {
+                let bytes = match serde_cbor::to_vec(&frame) {
+                    Ok(b) => b,
+                    Err(_) => continue,
+                };
+                if ws_tx.send(WsMessage::binary(bytes)).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let reader_conns = conns.clone();
+        let reader_task = tokio::spawn(async move {
+            while let Some(frame) = ws_rx.next().await {
+                let bytes = match frame {
+                    Ok(WsMessage::Binary(bytes)) => bytes,
+                    Ok(_) => continue,
+                    Err(_) => break,
+                };
+
+                let frame = match serde_cbor::from_slice::<RelayFrame>(&bytes) {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        warn!("bad relay frame: {}", e);
+                        continue;
+                    }
+                };
+
+                match frame {
+                    RelayFrame::Data { conn_id, payload } => {
+                        let sender = {
+                            let mut conns = reader_conns.lock().unwrap();
+                            conns
+                                .entry(conn_id)
+                                .or_insert_with(|| {
+                                    spawn_local_connection(conn_id, local_port, to_relay_tx.clone())
+                                })
+                                .tx
+                                .clone()
+                        };
+                        sender.send(payload).ok();
+                    }
+                    RelayFrame::Close { conn_id } => {
+                        if let Some(conn) = reader_conns.lock().unwrap().remove(&conn_id) {
+                            conn.task.abort();
+                        }
+                    }
+                    RelayFrame::Connected { .. } => {}
+                }
+            }
+        });
+
+        Ok((
+            Self {
+                writer_task,
+                reader_task,
+                conns,
+            },
+            public_address,
+        ))
+    }
+
+    /// Tears down the relay connection and every player connection it was
+    /// multiplexing: aborts the reader/writer tasks pumping the relay
+    /// websocket and every still-open `spawn_local_connection` task, so no
+    /// traffic keeps flowing after a tunnel is disabled.
+    pub fn disconnect(&self) {
+        self.writer_task.abort();
+        self.reader_task.abort();
+        for (_, conn) in self.conns.lock().unwrap().drain() {
+            conn.task.abort();
+        }
+    }
+}
+
+/// Opens a local connection to the Minecraft server for one player and pumps
+/// bytes both ways, forwarding local reads back to the relay as `Data`
+/// frames and forwarding the returned sender's payloads to the local write
+/// half. Sends the relay a `Close` frame when the local connection fails to
+/// open or its read half hits EOF or an error; a `Close` from the relay
+/// aborts the task instead.
+fn spawn_local_connection(
+    conn_id: u64,
+    local_port: u16,
+    to_relay: mpsc::UnboundedSender<RelayFrame>,
+) -> Connection {
+    let (tx, mut rx) = mpsc::unbounded_channel::<Vec<u8>>();
+
+    let task = tokio::spawn(async move {
+        let stream = match TcpStream::connect(("127.0.0.1", local_port)).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                error!("tunnel: failed to open local connection {}: {}", conn_id, e);
+                to_relay.send(RelayFrame::Close { conn_id }).ok();
+                return;
+            }
+        };
+        let (mut read_half, mut write_half) = stream.into_split();
+
+        let writer = tokio::spawn(async move {
+            while let Some(payload) = rx.recv().await {
+                if write_half.write_all(&payload).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut buf = [0u8; 8192];
+        loop {
+            match read_half.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    let frame = RelayFrame::Data {
+                        conn_id,
+                        payload: buf[..n].to_vec(),
+                    };
+                    if to_relay.send(frame).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        to_relay.send(RelayFrame::Close { conn_id }).ok();
+        writer.abort();
+    });
+
+    Connection { tx, task }
+}
@@ -0,0 +1,67 @@
+use crate::server::ServerData;
+use serde::{Deserialize, Serialize};
+use std::sync::PoisonError;
+
+/// A topic a client can subscribe to on the `cmd` websocket. `update_clients`
+/// only forwards a broadcast to clients whose subscription set contains its
+/// topic (or `AllServers`, which matches every server topic).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Topic {
+    AllServers,
+    Server(usize),
+    Console(usize),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum Command {
+    GetServers,
+    StartServer(usize),
+    StopServer(usize),
+    Subscribe(Topic),
+    Unsubscribe(Topic),
+    SendConsoleCommand(usize, String),
+    EnableTunnel(usize),
+    DisableTunnel(usize),
+    GetConsoleHistory(usize, Option<u64>),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum CommandResponse {
+    UpdateServers(Vec<ServerData>),
+    UpdateServer(usize, ServerData),
+    Subscribed(Topic),
+    Unsubscribed(Topic),
+    ConsoleLine(usize, String),
+    /// A page of persisted console lines for `Command::GetConsoleHistory`,
+    /// each tagged with the store key it was written under so the client
+    /// can pass the last one back as the next page's `since` cursor.
+    ConsoleHistory(usize, Vec<(u64, String)>),
+    Error(CommandError),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum CommandError {
+    LockPoisoned,
+    InvalidServerId(usize),
+    Io(String),
+}
+
+impl<T> From<PoisonError<T>> for CommandError {
+    fn from(_: PoisonError<T>) -> Self {
+        Self::LockPoisoned
+    }
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::LockPoisoned => write!(f, "internal state lock was poisoned"),
+            Self::InvalidServerId(id) => write!(f, "no server with id {}", id),
+            Self::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+pub type CommandResult = Result<CommandResponse, CommandError>;
@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ServerConfig {
+    pub name: String,
+    pub directory: String,
+    pub jar: String,
+    pub memory: u32,
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TlsConfig {
+    pub cert: String,
+    pub key: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Config {
+    pub version: u64,
+    pub servers: Vec<ServerConfig>,
+    /// Outbound relay endpoint used by `Command::EnableTunnel` to expose a
+    /// server without port forwarding. `None` disables tunneling entirely.
+    #[serde(default)]
+    pub relay: Option<String>,
+    /// Serve over HTTPS/WSS using this cert/key pair instead of plain HTTP.
+    /// Can also be set with `--tls-cert`/`--tls-key`, which take priority.
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+}
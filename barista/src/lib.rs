@@ -0,0 +1,3 @@
+pub mod command;
+pub mod config;
+pub mod server;
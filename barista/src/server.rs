@@ -0,0 +1,35 @@
+use crate::config::ServerConfig;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ServerStatus {
+    Stopped,
+    Starting,
+    Running,
+}
+
+/// The serializable, shared view of a server's state, sent to clients as
+/// part of `CommandResponse`. The daemon's own `Server` wraps this together
+/// with the runtime-only state (the child process handle) needed to manage it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ServerData {
+    pub id: usize,
+    pub config: ServerConfig,
+    pub status: ServerStatus,
+    pub pid: Option<u32>,
+    /// The public address assigned by the relay while a tunnel is active,
+    /// so the web UI can display it without a separate lookup.
+    pub tunnel_address: Option<String>,
+}
+
+impl ServerData {
+    pub fn new(id: usize, config: ServerConfig) -> Self {
+        Self {
+            id,
+            config,
+            status: ServerStatus::Stopped,
+            pid: None,
+            tunnel_address: None,
+        }
+    }
+}